@@ -1,55 +1,398 @@
-use crate::graph::{Graph, Node};
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::graph::{Edge, Graph, GraphError, GraphResult, Node};
+use std::any::Any;
+use std::collections::HashSet;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A boxed, type-erased [`Command`] as stored by the history stack.
+pub type DynCommand = Box<dyn Command>;
+
+/// A reversible mutation of a [`Graph`].
+///
+/// `apply` performs the change. `undo` does *not* mutate the graph; it inspects
+/// the current state and returns the concrete inverse command to apply later,
+/// so destructive operations can capture exactly what they need to restore
+/// (e.g. the edges cascaded away by a [`RemoveNode`]).
+///
+/// `merge` lets a run of same-kind edits collapse into a single undo step: when
+/// a new command is appended on top of `self`, `self.merge(&next)` may absorb
+/// it and return `true`, in which case `next` is dropped. The default refuses
+/// to merge.
 pub trait Command {
-    fn execute(&self);
-    fn rollback(&self);
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()>;
+    fn undo(&self, graph: &Graph) -> GraphResult<DynCommand>;
+
+    fn merge(&mut self, _next: &dyn Command) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Collect every edge incident to `node`, without duplicates.
+///
+/// `Graph::add_edge` does not reject duplicate edges, so the same edge can be
+/// incident to `node` more than once; deduplicating here means callers only
+/// ever issue one `remove_edge`/`add_edge` per distinct edge, which matters
+/// because `remove_edge` deletes *all* matching edges on a single call.
+fn incident_edges(graph: &Graph, node: Node) -> Vec<Edge> {
+    let mut seen: HashSet<Edge> = HashSet::new();
+    graph
+        .edges
+        .iter()
+        .copied()
+        .filter(|[a, b]| *a == node || *b == node)
+        .filter(|edge| seen.insert(*edge))
+        .collect()
 }
 
 pub struct AddNode {
-    graph: Rc<RefCell<Graph>>,
     node: Node,
 }
 
 impl AddNode {
-    pub fn new(graph: Rc<RefCell<Graph>>, node: Node) -> AddNode {
-        AddNode { graph, node }
+    pub fn new(node: Node) -> AddNode {
+        AddNode { node }
     }
 }
 
 impl Command for AddNode {
-    fn execute(&self) {
-        self.graph.borrow_mut().add_node(self.node);
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()> {
+        graph.add_node(self.node)
+    }
+
+    fn undo(&self, _graph: &Graph) -> GraphResult<DynCommand> {
+        Ok(Box::new(RemoveNode::new(self.node)))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Remove a node together with every edge incident to it.
+pub struct RemoveNode {
+    node: Node,
+}
+
+impl RemoveNode {
+    pub fn new(node: Node) -> RemoveNode {
+        RemoveNode { node }
+    }
+}
+
+impl Command for RemoveNode {
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()> {
+        if !graph.nodes.contains(&self.node) {
+            return Err(GraphError::NodeNotFound(self.node));
+        }
+        for [node1, node2] in incident_edges(graph, self.node) {
+            graph.remove_edge(node1, node2)?;
+        }
+        graph.remove_node(self.node)
+    }
+
+    fn undo(&self, graph: &Graph) -> GraphResult<DynCommand> {
+        if !graph.nodes.contains(&self.node) {
+            return Err(GraphError::NodeNotFound(self.node));
+        }
+        let edges = incident_edges(graph, self.node);
+        Ok(Box::new(RestoreNode::new(self.node, edges)))
     }
 
-    fn rollback(&self) {
-        self.graph.borrow_mut().remove_node(self.node);
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Re-add a node and the exact set of edges that were cascaded away with it.
+///
+/// Produced by [`RemoveNode::undo`]; its own inverse cascades the edges back
+/// out again, so a restore is itself undoable.
+pub struct RestoreNode {
+    node: Node,
+    edges: Vec<Edge>,
+}
+
+impl RestoreNode {
+    pub fn new(node: Node, edges: Vec<Edge>) -> RestoreNode {
+        RestoreNode { node, edges }
+    }
+}
+
+impl Command for RestoreNode {
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()> {
+        graph.add_node(self.node)?;
+        for [node1, node2] in &self.edges {
+            graph.add_edge(*node1, *node2)?;
+        }
+        Ok(())
+    }
+
+    fn undo(&self, _graph: &Graph) -> GraphResult<DynCommand> {
+        Ok(Box::new(RemoveNode::new(self.node)))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
 pub struct AddEdge {
-    graph: Rc<RefCell<Graph>>,
     node1: Node,
     node2: Node,
 }
 
 impl AddEdge {
-    pub fn new(graph: Rc<RefCell<Graph>>, node1: Node, node2: Node) -> AddEdge {
-        AddEdge {
-            graph,
-            node1,
-            node2,
-        }
+    pub fn new(node1: Node, node2: Node) -> AddEdge {
+        AddEdge { node1, node2 }
     }
 }
 
 impl Command for AddEdge {
-    fn execute(&self) {
-        self.graph.borrow_mut().add_edge(self.node1, self.node2);
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()> {
+        graph.add_edge(self.node1, self.node2)
+    }
+
+    fn undo(&self, _graph: &Graph) -> GraphResult<DynCommand> {
+        Ok(Box::new(RemoveEdge::new(self.node1, self.node2)))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct RemoveEdge {
+    node1: Node,
+    node2: Node,
+}
+
+impl RemoveEdge {
+    pub fn new(node1: Node, node2: Node) -> RemoveEdge {
+        RemoveEdge { node1, node2 }
+    }
+}
+
+impl Command for RemoveEdge {
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()> {
+        graph.remove_edge(self.node1, self.node2)
+    }
+
+    fn undo(&self, _graph: &Graph) -> GraphResult<DynCommand> {
+        Ok(Box::new(AddEdge::new(self.node1, self.node2)))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A batch of commands that apply and undo as a single unit.
+///
+/// `apply` runs the children in order; if any fails, the children already
+/// applied are rolled back before the error propagates, so the group is
+/// all-or-nothing. `undo` returns the children's inverses in reverse order,
+/// each generated against the state in which that child originally ran.
+pub struct CommandGroup {
+    commands: Vec<DynCommand>,
+}
+
+impl CommandGroup {
+    pub fn new(commands: Vec<DynCommand>) -> CommandGroup {
+        CommandGroup { commands }
+    }
+}
+
+impl Command for CommandGroup {
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()> {
+        let mut rollback: Vec<DynCommand> = vec![];
+        for child in &self.commands {
+            let inverse = match child
+                .undo(graph)
+                .and_then(|inverse| child.apply(graph).map(|()| inverse))
+            {
+                Ok(inverse) => inverse,
+                Err(err) => {
+                    while let Some(inverse) = rollback.pop() {
+                        inverse.apply(graph)?;
+                    }
+                    return Err(err);
+                }
+            };
+            rollback.push(inverse);
+        }
+        Ok(())
+    }
+
+    fn undo(&self, graph: &Graph) -> GraphResult<DynCommand> {
+        let mut simulated = graph.clone();
+        let mut inverses: Vec<DynCommand> = vec![];
+        for child in &self.commands {
+            inverses.push(child.undo(&simulated)?);
+            child.apply(&mut simulated)?;
+        }
+        inverses.reverse();
+        Ok(Box::new(CommandGroup::new(inverses)))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Relocate the node identified by `node` from coordinate `from` to `to`,
+/// carrying its incident edges with it.
+///
+/// Consecutive moves of the same node coalesce (see [`MoveNode::merge`]) so a
+/// click-and-drag lands as a single undo step rather than one per mouse event.
+pub struct MoveNode {
+    node: Node,
+    from: Node,
+    to: Node,
+}
+
+impl MoveNode {
+    pub fn new(node: Node, from: Node, to: Node) -> MoveNode {
+        MoveNode { node, from, to }
+    }
+}
+
+impl Command for MoveNode {
+    fn apply(&self, graph: &mut Graph) -> GraphResult<()> {
+        if self.from == self.to {
+            return Ok(());
+        }
+        // Validate every precondition before mutating anything: once we start
+        // removing edges there is no way to undo that partway through, so a
+        // failed move must be caught here, not mid-cascade.
+        if !graph.nodes.contains(&self.from) {
+            return Err(GraphError::NodeNotFound(self.from));
+        }
+        if graph.nodes.contains(&self.to) {
+            return Err(GraphError::DuplicateNode(self.to));
+        }
+        let edges = incident_edges(graph, self.from);
+        let moved_edges: Vec<Edge> = edges
+            .iter()
+            .map(|[node1, node2]| {
+                let node1 = if *node1 == self.from { self.to } else { *node1 };
+                let node2 = if *node2 == self.from { self.to } else { *node2 };
+                [node1, node2]
+            })
+            .collect();
+        for [node1, node2] in &edges {
+            graph.remove_edge(*node1, *node2)?;
+        }
+        graph.remove_node(self.from)?;
+        graph.add_node(self.to)?;
+        for [node1, node2] in moved_edges {
+            graph.add_edge(node1, node2)?;
+        }
+        Ok(())
+    }
+
+    fn undo(&self, _graph: &Graph) -> GraphResult<DynCommand> {
+        Ok(Box::new(MoveNode::new(self.node, self.to, self.from)))
     }
 
-    fn rollback(&self) {
-        self.graph.borrow_mut().remove_edge(self.node1, self.node2);
+    fn merge(&mut self, next: &dyn Command) -> bool {
+        if let Some(other) = next.as_any().downcast_ref::<MoveNode>() {
+            if other.node == self.node {
+                // Extend this drag to the new target, keeping the original start.
+                self.to = other.to;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A tagged, owned representation of every [`Command`] kind, used as the
+/// serialized form of the history timeline.
+///
+/// Round-trips through [`AnyCommand::from_command`] and
+/// [`AnyCommand::into_command`] so a boxed `dyn Command` can be persisted and
+/// later reconstructed.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+pub enum AnyCommand {
+    AddNode { node: Node },
+    RemoveNode { node: Node },
+    RestoreNode { node: Node, edges: Vec<Edge> },
+    AddEdge { node1: Node, node2: Node },
+    RemoveEdge { node1: Node, node2: Node },
+    MoveNode { node: Node, from: Node, to: Node },
+    Group { commands: Vec<AnyCommand> },
+}
+
+#[cfg(feature = "serde")]
+impl AnyCommand {
+    /// Tag an existing command for serialization.
+    ///
+    /// `Command` is meant to be implemented by consumers of the crate, so a
+    /// command of an unrecognized concrete type is an expected possibility,
+    /// not a bug; it is reported as a [`GraphError::Serialization`] rather
+    /// than a panic.
+    pub fn from_command(command: &dyn Command) -> GraphResult<AnyCommand> {
+        let any = command.as_any();
+        if let Some(c) = any.downcast_ref::<AddNode>() {
+            Ok(AnyCommand::AddNode { node: c.node })
+        } else if let Some(c) = any.downcast_ref::<RemoveNode>() {
+            Ok(AnyCommand::RemoveNode { node: c.node })
+        } else if let Some(c) = any.downcast_ref::<RestoreNode>() {
+            Ok(AnyCommand::RestoreNode {
+                node: c.node,
+                edges: c.edges.clone(),
+            })
+        } else if let Some(c) = any.downcast_ref::<AddEdge>() {
+            Ok(AnyCommand::AddEdge {
+                node1: c.node1,
+                node2: c.node2,
+            })
+        } else if let Some(c) = any.downcast_ref::<RemoveEdge>() {
+            Ok(AnyCommand::RemoveEdge {
+                node1: c.node1,
+                node2: c.node2,
+            })
+        } else if let Some(c) = any.downcast_ref::<MoveNode>() {
+            Ok(AnyCommand::MoveNode {
+                node: c.node,
+                from: c.from,
+                to: c.to,
+            })
+        } else if let Some(c) = any.downcast_ref::<CommandGroup>() {
+            Ok(AnyCommand::Group {
+                commands: c
+                    .commands
+                    .iter()
+                    .map(|child| AnyCommand::from_command(child.as_ref()))
+                    .collect::<GraphResult<Vec<_>>>()?,
+            })
+        } else {
+            Err(GraphError::Serialization(
+                "command cannot be serialized: unknown concrete type".to_string(),
+            ))
+        }
+    }
+
+    /// Rebuild a boxed command from its tagged form.
+    pub fn into_command(self) -> DynCommand {
+        match self {
+            AnyCommand::AddNode { node } => Box::new(AddNode::new(node)),
+            AnyCommand::RemoveNode { node } => Box::new(RemoveNode::new(node)),
+            AnyCommand::RestoreNode { node, edges } => Box::new(RestoreNode::new(node, edges)),
+            AnyCommand::AddEdge { node1, node2 } => Box::new(AddEdge::new(node1, node2)),
+            AnyCommand::RemoveEdge { node1, node2 } => Box::new(RemoveEdge::new(node1, node2)),
+            AnyCommand::MoveNode { node, from, to } => Box::new(MoveNode::new(node, from, to)),
+            AnyCommand::Group { commands } => Box::new(CommandGroup::new(
+                commands.into_iter().map(AnyCommand::into_command).collect(),
+            )),
+        }
     }
 }
@@ -1,8 +1,11 @@
-use crate::{commands::AddNode, graph::Graph, history::History};
+use crate::{commands::AddNode, graph::Graph, graph::GraphResult, history::History};
 
-pub fn example() {
+pub fn example() -> GraphResult<()> {
     let mut graph: Graph = Graph::new();
     let mut history: History = History::new();
 
-    history.append(AddNode::new(&graph, [0, 0]));
+    history.append(Box::new(AddNode::new([0, 0])));
+    history.execute(&mut graph)?;
+
+    Ok(())
 }
@@ -1,12 +1,46 @@
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 pub type Node = [u8; 2];
 pub type Edge = [Node; 2];
 
-#[derive(Debug)]
+/// The result type returned by every fallible graph operation.
+pub type GraphResult<T> = Result<T, GraphError>;
+
+/// Everything that can go wrong while mutating a [`Graph`].
+///
+/// Commands validate against these before touching the graph so that the
+/// undo/redo stack can never drive the graph into an inconsistent state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    /// A node was expected to exist but does not.
+    NodeNotFound(Node),
+    /// An edge was expected to exist but does not.
+    EdgeNotFound(Edge),
+    /// A node with the same coordinates is already present.
+    DuplicateNode(Node),
+    /// An edge references an endpoint that has not been added.
+    EdgeEndpointMissing(Node),
+    /// A document failed to serialize or deserialize.
+    #[cfg(feature = "serde")]
+    Serialization(String),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Graph {
     pub nodes: Vec<Node>,
     pub edges: Vec<Edge>,
 }
 
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Graph {
     pub fn new() -> Graph {
         Graph {
@@ -15,33 +49,176 @@ impl Graph {
         }
     }
 
-    pub fn add_node(&mut self, node: Node) {
+    pub fn add_node(&mut self, node: Node) -> GraphResult<()> {
+        if self.nodes.contains(&node) {
+            return Err(GraphError::DuplicateNode(node));
+        }
         self.nodes.push(node);
+        Ok(())
     }
 
-    pub fn remove_node(&mut self, node: Node) {
+    pub fn remove_node(&mut self, node: Node) -> GraphResult<()> {
+        if !self.nodes.contains(&node) {
+            return Err(GraphError::NodeNotFound(node));
+        }
         self.nodes.retain(|&n| n != node);
+        Ok(())
     }
 
-    pub fn add_edge(&mut self, node1: Node, node2: Node) {
+    pub fn add_edge(&mut self, node1: Node, node2: Node) -> GraphResult<()> {
+        if !self.nodes.contains(&node1) {
+            return Err(GraphError::EdgeEndpointMissing(node1));
+        }
+        if !self.nodes.contains(&node2) {
+            return Err(GraphError::EdgeEndpointMissing(node2));
+        }
         self.edges.push([node1, node2]);
+        Ok(())
+    }
+
+    pub fn remove_edge(&mut self, node1: Node, node2: Node) -> GraphResult<()> {
+        let edge = [node1, node2];
+        if !self.edges.contains(&edge) {
+            return Err(GraphError::EdgeNotFound(edge));
+        }
+        self.edges.retain(|&e| e != edge);
+        Ok(())
+    }
+
+    /// The nodes reachable from `node` along a single outgoing edge.
+    pub fn successors(&self, node: Node) -> Vec<Node> {
+        self.edges
+            .iter()
+            .filter(|[from, _]| *from == node)
+            .map(|[_, to]| *to)
+            .collect()
+    }
+
+    /// The nodes from which `node` is reachable along a single incoming edge.
+    pub fn predecessors(&self, node: Node) -> Vec<Node> {
+        self.edges
+            .iter()
+            .filter(|[_, to]| *to == node)
+            .map(|[from, _]| *from)
+            .collect()
+    }
+
+    /// Call `f` once for every node, in insertion order.
+    pub fn each_node(&self, mut f: impl FnMut(Node)) {
+        for &node in &self.nodes {
+            f(node);
+        }
+    }
+
+    /// Call `f` once for every edge, in insertion order.
+    pub fn each_edge(&self, mut f: impl FnMut(Edge)) {
+        for &edge in &self.edges {
+            f(edge);
+        }
     }
 
-    pub fn remove_edge(&mut self, node1: Node, node2: Node) {
-        self.edges.retain(|&n| n != [node1, node2]);
+    /// Visit every node reachable from `start` following successor edges.
+    ///
+    /// Uses an explicit stack and a visited set; the returned vector lists the
+    /// nodes in the order they were first visited.
+    pub fn depth_first_search(&self, start: Node) -> Vec<Node> {
+        let mut visited: HashSet<Node> = HashSet::new();
+        let mut order: Vec<Node> = vec![];
+        let mut stack: Vec<Node> = vec![start];
+        while let Some(node) = stack.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            order.push(node);
+            for successor in self.successors(node) {
+                if !visited.contains(&successor) {
+                    stack.push(successor);
+                }
+            }
+        }
+        order
+    }
+
+    /// Report whether the graph contains a directed cycle.
+    ///
+    /// Runs a depth-first search with three-colour (white/grey/black) marking;
+    /// encountering a grey node means we followed a back edge, i.e. a cycle.
+    pub fn has_cycle(&self) -> bool {
+        let mut colour: HashMap<Node, Colour> = HashMap::new();
+        for &node in &self.nodes {
+            if !colour.contains_key(&node) && self.visit(node, &mut colour) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn visit(&self, node: Node, colour: &mut HashMap<Node, Colour>) -> bool {
+        colour.insert(node, Colour::Grey);
+        for successor in self.successors(node) {
+            match colour.get(&successor) {
+                Some(Colour::Grey) => return true,
+                Some(Colour::Black) => {}
+                None => {
+                    if self.visit(successor, colour) {
+                        return true;
+                    }
+                }
+            }
+        }
+        colour.insert(node, Colour::Black);
+        false
     }
 }
 
+/// Visit state for the three-colour cycle search.
+enum Colour {
+    Grey,
+    Black,
+}
+
 #[test]
 fn test_graph() {
     let mut graph = Graph::new();
-    graph.add_node([0, 0]);
-    graph.add_node([1, 1]);
+    graph.add_node([0, 0]).unwrap();
+    graph.add_node([1, 1]).unwrap();
     assert_eq!(graph.nodes, vec![[0, 0], [1, 1]]);
 
-    graph.remove_node([1, 1]);
+    graph.remove_node([1, 1]).unwrap();
     assert_eq!(graph.nodes, vec![[0, 0]]);
 
-    graph.add_edge([0, 0], [1, 1]);
-    assert_eq!(graph.edges, vec![[[0, 0], [1, 1]]])
+    graph.add_node([1, 1]).unwrap();
+    graph.add_edge([0, 0], [1, 1]).unwrap();
+    assert_eq!(graph.edges, vec![[[0, 0], [1, 1]]]);
+
+    // Validation rejects inconsistent mutations rather than performing them.
+    assert_eq!(graph.add_node([0, 0]), Err(GraphError::DuplicateNode([0, 0])));
+    assert_eq!(
+        graph.add_edge([0, 0], [9, 9]),
+        Err(GraphError::EdgeEndpointMissing([9, 9]))
+    );
+    assert_eq!(graph.remove_node([5, 5]), Err(GraphError::NodeNotFound([5, 5])));
+    assert_eq!(
+        graph.remove_edge([0, 0], [9, 9]),
+        Err(GraphError::EdgeNotFound([[0, 0], [9, 9]]))
+    );
+}
+
+#[test]
+fn test_traversal() {
+    let mut graph = Graph::new();
+    for node in [[0, 0], [1, 1], [2, 2]] {
+        graph.add_node(node).unwrap();
+    }
+    graph.add_edge([0, 0], [1, 1]).unwrap();
+    graph.add_edge([1, 1], [2, 2]).unwrap();
+
+    assert_eq!(graph.successors([0, 0]), vec![[1, 1]]);
+    assert_eq!(graph.predecessors([2, 2]), vec![[1, 1]]);
+    assert_eq!(graph.depth_first_search([0, 0]), vec![[0, 0], [1, 1], [2, 2]]);
+    assert!(!graph.has_cycle());
+
+    // Close the loop back to the start and the cycle is detected.
+    graph.add_edge([2, 2], [0, 0]).unwrap();
+    assert!(graph.has_cycle());
 }
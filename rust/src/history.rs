@@ -0,0 +1,246 @@
+use crate::commands::{CommandGroup, DynCommand};
+use crate::graph::{Graph, GraphResult};
+use std::vec::IntoIter;
+
+#[cfg(feature = "serde")]
+use crate::commands::AnyCommand;
+#[cfg(feature = "serde")]
+use crate::graph::GraphError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An undo/redo stack of `(forward, inverse)` command pairs.
+///
+/// Commands are staged with [`append`](History::append) and only touch the
+/// graph once [`execute`](History::execute) flushes the pending tail. At that
+/// point each forward command's inverse is generated from the current graph
+/// state and stored alongside it, so undo/redo stay symmetric even for
+/// cascading deletes.
+///
+/// `cursor` tracks how many committed entries are currently applied to the
+/// graph, while `revision` tracks the logical tip of the timeline (which moves
+/// with [`undo`](History::undo)/[`redo`](History::redo)).
+pub struct History {
+    pub history: Vec<(DynCommand, DynCommand)>,
+    pending: Vec<DynCommand>,
+    /// Commands absorbed into the forward command at the tip of `history` by
+    /// [`append`](History::append). Their effect still needs to be applied to
+    /// the graph, but they must not create a new `history` entry of their own.
+    merged: Vec<DynCommand>,
+    transaction: Option<Vec<DynCommand>>,
+    pub cursor: usize,
+    pub revision: usize,
+}
+
+/// Put a command that failed partway through a batch back at the front of
+/// `buffer`, followed by whatever the batch's iterator had left, so a failed
+/// `execute` retries the whole unprocessed tail instead of dropping it.
+fn requeue<T>(buffer: &mut Vec<T>, failed: T, rest: IntoIter<T>) {
+    buffer.push(failed);
+    buffer.extend(rest);
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            history: vec![],
+            pending: vec![],
+            merged: vec![],
+            transaction: None,
+            cursor: 0,
+            revision: 0,
+        }
+    }
+
+    /// Start accumulating appended commands into a single logical operation.
+    ///
+    /// Until [`commit`](History::commit), `append` collects commands instead of
+    /// staging them individually, so the whole batch counts as one `revision`
+    /// and undoes as one step.
+    pub fn begin_transaction(&mut self) {
+        self.transaction = Some(vec![]);
+    }
+
+    /// Close the open transaction, staging its commands as one group.
+    pub fn commit(&mut self) {
+        if let Some(commands) = self.transaction.take() {
+            if !commands.is_empty() {
+                self.append(Box::new(CommandGroup::new(commands)));
+            }
+        }
+    }
+
+    /// Stage a command at the logical tip, discarding any redo tail.
+    ///
+    /// If a command at the tip can absorb `command` (e.g. another step of the
+    /// same node drag), it is merged in place and `command` is dropped rather
+    /// than staged as its own entry, so `revision` does not grow. The tip
+    /// checked is whichever is still outstanding: the last pending command if
+    /// one hasn't been executed yet, otherwise the last *committed* command in
+    /// `history` — real usage calls `execute` after every `append`, so pending
+    /// is normally empty by the next call and a merge that only ever looked at
+    /// `pending` would never fire. Merging into an already-applied `history`
+    /// entry still needs its effect applied to the graph, so `command` is
+    /// queued in `merged` for the next [`execute`](History::execute) rather
+    /// than being discarded outright. Merging is only attempted at the tip
+    /// (`cursor == history.len()`) so a pending redo tail or an unrelated edit
+    /// is never silently swallowed.
+    pub fn append(&mut self, command: DynCommand) {
+        if let Some(transaction) = self.transaction.as_mut() {
+            transaction.push(command);
+            return;
+        }
+        if self.cursor == self.history.len() {
+            if let Some(top) = self.pending.last_mut() {
+                if top.merge(command.as_ref()) {
+                    return;
+                }
+            } else if let Some((top, _)) = self.history.last_mut() {
+                if top.merge(command.as_ref()) {
+                    self.merged.push(command);
+                    return;
+                }
+            }
+        }
+        self.history.truncate(self.cursor);
+        self.pending.push(command);
+        self.revision += 1;
+    }
+
+    /// Apply every staged command, recording its generated inverse.
+    ///
+    /// If a command fails to apply, the error is propagated and the cursor is
+    /// left where it was, so a failed edit never advances history. The
+    /// command that failed and everything queued after it are put back on
+    /// `self.pending`/`self.merged` rather than dropped, so a later `execute`
+    /// call retries them instead of silently losing them.
+    ///
+    /// Commands that `append` merged into the `history` tip are applied first:
+    /// the tip's forward command already reflects the merged result, so its
+    /// inverse is regenerated from the current graph state before the merged
+    /// command runs, and the `history` entry is updated in place rather than
+    /// growing.
+    pub fn execute(&mut self, graph: &mut Graph) -> GraphResult<()> {
+        let mut merged = std::mem::take(&mut self.merged).into_iter();
+        while let Some(command) = merged.next() {
+            let inverse = match self
+                .history
+                .last()
+                .expect("a merged command always has a history tip to merge into")
+                .0
+                .undo(graph)
+            {
+                Ok(inverse) => inverse,
+                Err(err) => {
+                    requeue(&mut self.merged, command, merged);
+                    return Err(err);
+                }
+            };
+            if let Err(err) = command.apply(graph) {
+                requeue(&mut self.merged, command, merged);
+                return Err(err);
+            }
+            if let Some(entry) = self.history.last_mut() {
+                entry.1 = inverse;
+            }
+        }
+        let mut pending = std::mem::take(&mut self.pending).into_iter();
+        while let Some(forward) = pending.next() {
+            let inverse = match forward.undo(graph) {
+                Ok(inverse) => inverse,
+                Err(err) => {
+                    requeue(&mut self.pending, forward, pending);
+                    return Err(err);
+                }
+            };
+            if let Err(err) = forward.apply(graph) {
+                requeue(&mut self.pending, forward, pending);
+                return Err(err);
+            }
+            self.history.push((forward, inverse));
+            self.cursor += 1;
+        }
+        Ok(())
+    }
+
+    /// Apply the inverse of the most recently applied command.
+    pub fn undo(&mut self, graph: &mut Graph) -> GraphResult<()> {
+        if self.cursor == 0 {
+            return Ok(());
+        }
+        let (_, inverse) = &self.history[self.cursor - 1];
+        inverse.apply(graph)?;
+        self.cursor -= 1;
+        self.revision -= 1;
+        Ok(())
+    }
+
+    /// Re-apply the forward command that was most recently undone.
+    pub fn redo(&mut self, graph: &mut Graph) -> GraphResult<()> {
+        if self.cursor >= self.history.len() {
+            return Ok(());
+        }
+        let (forward, _) = &self.history[self.cursor];
+        forward.apply(graph)?;
+        self.cursor += 1;
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+/// The on-disk form of a [`History`]: the forward command timeline plus the
+/// cursor and revision needed to restore its position.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct HistorySnapshot {
+    commands: Vec<AnyCommand>,
+    cursor: usize,
+    revision: usize,
+}
+
+#[cfg(feature = "serde")]
+impl History {
+    /// Serialize the committed timeline to `writer` as JSON.
+    pub fn save<W: std::io::Write>(&self, writer: W) -> GraphResult<()> {
+        let snapshot = HistorySnapshot {
+            commands: self
+                .history
+                .iter()
+                .map(|(forward, _)| AnyCommand::from_command(forward.as_ref()))
+                .collect::<GraphResult<Vec<_>>>()?,
+            cursor: self.cursor,
+            revision: self.revision,
+        };
+        serde_json::to_writer(writer, &snapshot)
+            .map_err(|err| GraphError::Serialization(err.to_string()))
+    }
+
+    /// Reconstruct a history from `reader`, replaying it onto `graph`.
+    ///
+    /// Every forward command is rebuilt and applied to regenerate its inverse,
+    /// then the timeline is rewound to the saved cursor so `graph` ends up in
+    /// exactly the state it was saved in.
+    pub fn load<R: std::io::Read>(reader: R, graph: &mut Graph) -> GraphResult<History> {
+        let snapshot: HistorySnapshot = serde_json::from_reader(reader)
+            .map_err(|err| GraphError::Serialization(err.to_string()))?;
+        let mut history = History::new();
+        for command in snapshot.commands {
+            // Staged directly rather than through `append`, so saved entries
+            // are never merged into each other on the way back in.
+            history.pending.push(command.into_command());
+            history.revision += 1;
+        }
+        history.execute(graph)?;
+        while history.cursor > snapshot.cursor {
+            history.undo(graph)?;
+        }
+        history.revision = snapshot.revision;
+        Ok(history)
+    }
+}
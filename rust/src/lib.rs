@@ -0,0 +1,4 @@
+pub mod commands;
+pub mod example;
+pub mod graph;
+pub mod history;
@@ -0,0 +1,69 @@
+#![cfg(feature = "serde")]
+
+use command_pattern::commands::{AddEdge, AddNode, AnyCommand, Command, DynCommand};
+use command_pattern::graph::{Graph, GraphError, GraphResult};
+use command_pattern::history::History;
+use std::any::Any;
+
+#[test]
+fn test_save_load_round_trip() {
+    let mut graph = Graph::new();
+    let mut history = History::new();
+
+    history.append(Box::new(AddNode::new([0, 0])));
+    history.append(Box::new(AddNode::new([1, 1])));
+    history.append(Box::new(AddEdge::new([0, 0], [1, 1])));
+    history.execute(&mut graph).unwrap();
+    history.undo(&mut graph).unwrap();
+
+    let mut bytes: Vec<u8> = vec![];
+    history.save(&mut bytes).unwrap();
+
+    let mut loaded_graph = Graph::new();
+    let loaded_history = History::load(bytes.as_slice(), &mut loaded_graph).unwrap();
+
+    assert_eq!(loaded_graph.nodes, graph.nodes);
+    assert_eq!(loaded_graph.edges, graph.edges);
+    assert_eq!(loaded_history.cursor, history.cursor);
+    assert_eq!(loaded_history.revision, history.revision);
+}
+
+/// A `Command` implemented outside the crate's own seven types, standing in for
+/// a consumer's custom command.
+struct NoOpCommand;
+
+impl Command for NoOpCommand {
+    fn apply(&self, _graph: &mut Graph) -> GraphResult<()> {
+        Ok(())
+    }
+
+    fn undo(&self, _graph: &Graph) -> GraphResult<DynCommand> {
+        Ok(Box::new(NoOpCommand))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[test]
+fn test_from_command_rejects_unknown_command() {
+    match AnyCommand::from_command(&NoOpCommand) {
+        Err(GraphError::Serialization(_)) => {}
+        _ => panic!("expected a Serialization error"),
+    }
+}
+
+#[test]
+fn test_save_rejects_history_with_unknown_command() {
+    let mut graph = Graph::new();
+    let mut history = History::new();
+    history.append(Box::new(AddNode::new([0, 0])));
+    history.execute(&mut graph).unwrap();
+
+    // Swap in a command the serializer cannot tag.
+    history.history[0] = (Box::new(NoOpCommand), Box::new(NoOpCommand));
+
+    let mut bytes: Vec<u8> = vec![];
+    assert!(history.save(&mut bytes).is_err());
+}
@@ -1,58 +1,205 @@
-use std::{cell::RefCell, rc::Rc};
-
-use command_pattern::commands::{AddEdge, AddNode};
-use command_pattern::graph::{Edge, Graph, Node};
+use command_pattern::commands::{AddEdge, AddNode, Command, MoveNode, RemoveNode};
+use command_pattern::graph::{Edge, Graph, GraphError, Node};
 use command_pattern::history::History;
 
+#[test]
+fn test_transaction() {
+    let mut graph = Graph::new();
+    let mut history = History::new();
+
+    // "Insert a connected node": two edits that should undo as one unit.
+    history.append(Box::new(AddNode::new([0, 0])));
+    history.execute(&mut graph).unwrap();
+
+    history.begin_transaction();
+    history.append(Box::new(AddNode::new([1, 1])));
+    history.append(Box::new(AddEdge::new([0, 0], [1, 1])));
+    history.commit();
+    assert_eq!(history.revision, 2);
+
+    history.execute(&mut graph).unwrap();
+    assert_eq!(graph.nodes, [[0, 0], [1, 1]]);
+    assert_eq!(graph.edges, [[[0, 0], [1, 1]]]);
+
+    // A single undo reverses the whole group.
+    history.undo(&mut graph).unwrap();
+    assert_eq!(history.revision, 1);
+    assert_eq!(graph.nodes, [[0, 0]]);
+    let empty_edge_vec: Vec<Edge> = vec![];
+    assert_eq!(graph.edges, empty_edge_vec);
+}
+
 #[test]
 fn test() {
-    let graph = Rc::new(RefCell::new(Graph::new()));
+    let mut graph = Graph::new();
     let mut history: History = History::new();
 
     assert_eq!(history.revision, 0);
 
     // Add a node to the graph at (0, 0)
-    history.append(Box::new(AddNode::new(graph.clone(), [0, 0])));
+    history.append(Box::new(AddNode::new([0, 0])));
 
     // Add a node to the graph at (1, 1)
-    history.append(Box::new(AddNode::new(graph.clone(), [1, 1])));
+    history.append(Box::new(AddNode::new([1, 1])));
 
     // Check that the graph is still unchanged
     assert_eq!(history.cursor, 0);
     assert_eq!(history.revision, 2);
     let empty_node_vec: Vec<Node> = vec![];
-    assert_eq!(graph.borrow().nodes, empty_node_vec);
+    assert_eq!(graph.nodes, empty_node_vec);
 
     // Execute the commands and check that the changes have now been made
-    history.execute();
+    history.execute(&mut graph).unwrap();
     assert_eq!(history.cursor, 2);
     assert_eq!(history.revision, 2);
-    assert_eq!(graph.borrow().nodes, [[0, 0], [1, 1]]);
+    assert_eq!(graph.nodes, [[0, 0], [1, 1]]);
 
     // Connect the two nodes into a vertex
-    history.append(Box::new(AddEdge::new(graph.clone(), [0, 0], [1, 1])));
-    history.execute();
+    history.append(Box::new(AddEdge::new([0, 0], [1, 1])));
+    history.execute(&mut graph).unwrap();
     assert_eq!(history.revision, 3);
-    assert_eq!(graph.borrow().edges, [[[0, 0], [1, 1]]]);
+    assert_eq!(graph.edges, [[[0, 0], [1, 1]]]);
 
     // Undo the last action
-    history.undo();
+    history.undo(&mut graph).unwrap();
     assert_eq!(history.revision, 2);
     let empty_edge_vec: Vec<Edge> = vec![];
-    assert_eq!(graph.borrow().edges, empty_edge_vec);
+    assert_eq!(graph.edges, empty_edge_vec);
     assert_eq!(history.history.len(), 3);
 
     // Redo the last action
-    history.redo();
+    history.redo(&mut graph).unwrap();
     assert_eq!(history.revision, 3);
-    assert_eq!(graph.borrow().edges, [[[0, 0], [1, 1]]]);
+    assert_eq!(graph.edges, [[[0, 0], [1, 1]]]);
 
     // Undo the last action and perform a new action, rewriting the history
-    history.undo();
-    history.append(Box::new(AddNode::new(graph.clone(), [2, 2])));
-    history.execute();
+    history.undo(&mut graph).unwrap();
+    history.append(Box::new(AddNode::new([2, 2])));
+    history.execute(&mut graph).unwrap();
     assert_eq!(history.revision, 3);
-    assert_eq!(graph.borrow().nodes, [[0, 0], [1, 1], [2, 2]]);
-    assert_eq!(graph.borrow().edges, empty_edge_vec);
+    assert_eq!(graph.nodes, [[0, 0], [1, 1], [2, 2]]);
+    assert_eq!(graph.edges, empty_edge_vec);
     assert_eq!(history.history.len(), 3);
 }
+
+#[test]
+fn test_remove_node_cascade() {
+    let mut graph = Graph::new();
+    graph.add_node([0, 0]).unwrap();
+    graph.add_node([1, 1]).unwrap();
+    graph.add_node([2, 2]).unwrap();
+    graph.add_edge([0, 0], [1, 1]).unwrap();
+    graph.add_edge([0, 0], [2, 2]).unwrap();
+    // A duplicate edge between the same pair of nodes is possible because
+    // `add_edge` has no uniqueness check; removal must still be all-or-nothing.
+    graph.add_edge([0, 0], [1, 1]).unwrap();
+
+    RemoveNode::new([0, 0]).apply(&mut graph).unwrap();
+
+    assert_eq!(graph.nodes, [[1, 1], [2, 2]]);
+    let empty_edge_vec: Vec<Edge> = vec![];
+    assert_eq!(graph.edges, empty_edge_vec);
+}
+
+#[test]
+fn test_move_node_rejects_occupied_target_without_corrupting_graph() {
+    let mut graph = Graph::new();
+    graph.add_node([0, 0]).unwrap();
+    graph.add_node([5, 5]).unwrap();
+    graph.add_edge([0, 0], [5, 5]).unwrap();
+
+    // The target is already occupied, so the move must fail without
+    // touching the node or its edges.
+    assert_eq!(
+        MoveNode::new([0, 0], [0, 0], [5, 5]).apply(&mut graph),
+        Err(GraphError::DuplicateNode([5, 5]))
+    );
+    assert_eq!(graph.nodes, [[0, 0], [5, 5]]);
+    assert_eq!(graph.edges, [[[0, 0], [5, 5]]]);
+}
+
+#[test]
+fn test_execute_requeues_pending_tail_after_failure() {
+    let mut graph = Graph::new();
+    let mut history = History::new();
+
+    history.append(Box::new(AddNode::new([0, 0])));
+    // Duplicate of the first command: fails to apply.
+    history.append(Box::new(AddNode::new([0, 0])));
+    history.append(Box::new(AddNode::new([2, 2])));
+
+    assert_eq!(
+        history.execute(&mut graph),
+        Err(GraphError::DuplicateNode([0, 0]))
+    );
+    // Only the first command got through; the rest must still be pending,
+    // not silently dropped.
+    assert_eq!(graph.nodes, [[0, 0]]);
+
+    // Retrying while the duplicate is still queued fails the same way again,
+    // rather than reporting success with the failed command and [2, 2] lost.
+    assert_eq!(
+        history.execute(&mut graph),
+        Err(GraphError::DuplicateNode([0, 0]))
+    );
+    assert_eq!(graph.nodes, [[0, 0]]);
+}
+
+#[test]
+fn test_execute_requeues_merged_tail_after_failure() {
+    let mut graph = Graph::new();
+    graph.add_node([0, 0]).unwrap();
+    graph.add_node([9, 9]).unwrap();
+    let mut history = History::new();
+
+    history.append(Box::new(MoveNode::new([0, 0], [0, 0], [1, 1])));
+    history.execute(&mut graph).unwrap();
+
+    // Three more moves of the same node, coalesced into the tip before
+    // `execute` ever runs. The middle one targets [9, 9], which is occupied
+    // by an unrelated node, so it fails to apply.
+    history.append(Box::new(MoveNode::new([0, 0], [1, 1], [2, 2])));
+    history.append(Box::new(MoveNode::new([0, 0], [2, 2], [9, 9])));
+    history.append(Box::new(MoveNode::new([0, 0], [9, 9], [4, 4])));
+    assert_eq!(history.revision, 1);
+
+    assert_eq!(
+        history.execute(&mut graph),
+        Err(GraphError::DuplicateNode([9, 9]))
+    );
+    // The first of the three went through; [0, 0] must now be at [2, 2].
+    assert_eq!(graph.nodes, [[9, 9], [2, 2]]);
+
+    // Resolve the conflict and retry: the second and third moves must still
+    // be queued, not lost, so the whole drag completes.
+    graph.remove_node([9, 9]).unwrap();
+    history.execute(&mut graph).unwrap();
+    assert_eq!(graph.nodes, [[4, 4]]);
+}
+
+#[test]
+fn test_move_node_coalesces_append_execute_per_step() {
+    // Mirrors a click-and-drag: each incremental move is appended and
+    // executed immediately, the way example.rs does.
+    let mut graph = Graph::new();
+    graph.add_node([0, 0]).unwrap();
+    let mut history = History::new();
+
+    let steps = [[1, 1], [2, 2], [3, 3], [4, 4], [5, 5]];
+    let mut from = [0, 0];
+    for to in steps {
+        history.append(Box::new(MoveNode::new([0, 0], from, to)));
+        history.execute(&mut graph).unwrap();
+        from = to;
+    }
+
+    // Five moves of the same node collapse into a single undo step.
+    assert_eq!(history.revision, 1);
+    assert_eq!(history.history.len(), 1);
+    assert_eq!(graph.nodes, [[5, 5]]);
+
+    // Undo still returns all the way to the original position, not just the
+    // previous step.
+    history.undo(&mut graph).unwrap();
+    assert_eq!(graph.nodes, [[0, 0]]);
+}